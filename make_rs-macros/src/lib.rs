@@ -0,0 +1,112 @@
+//! The `cmd!` proc macro: parses a `git rev-parse {branch}`-style template at
+//! compile time and expands it into `Cmd::arg`/`Cmd::args` calls.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+enum Piece {
+    Literal(String),
+    Var(syn::Expr),
+}
+
+/// `cmd!("git rev-parse {branch}")` expands to a `make_rs::Cmd` built from
+/// `"git"`, `"rev-parse"` and the value of the local `branch` by value.
+/// `{branch}` may be a whole whitespace-delimited word or sit next to
+/// literal text in one (`{port}/tcp`); `{rest...}` must be its own word
+/// and splices every item of an iterable in as its own arg.
+#[proc_macro]
+pub fn cmd(input: TokenStream) -> TokenStream {
+    let template = parse_macro_input!(input as LitStr);
+    let text = template.value();
+
+    let mut words = text.split_whitespace();
+    let program = match words.next() {
+        Some(program) => program,
+        None => {
+            return syn::Error::new(template.span(), "cmd! template must not be empty")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let mut arg_calls = Vec::new();
+    for word in words {
+        if let Some(ident) = word.strip_prefix('{').and_then(|w| w.strip_suffix("...}")) {
+            match syn::parse_str::<syn::Expr>(ident) {
+                Ok(expr) => arg_calls.push(quote! { .args(#expr) }),
+                Err(_) => {
+                    return syn::Error::new(
+                        Span::call_site(),
+                        format!("`{}` is not a valid expression in a {{...}} spread", ident),
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+            }
+            continue;
+        }
+
+        match parse_word(word) {
+            Ok(pieces) => {
+                // Both a bare `{var}` and a `{var}` embedded next to literal
+                // text go through the same `Display`-based conversion, so
+                // e.g. a `u16` port works in either position.
+                let mut fmt = String::new();
+                let mut exprs = Vec::new();
+                for piece in &pieces {
+                    match piece {
+                        Piece::Literal(lit) => fmt.push_str(&lit.replace('{', "{{").replace('}', "}}")),
+                        Piece::Var(expr) => {
+                            fmt.push_str("{}");
+                            exprs.push(expr.clone());
+                        }
+                    }
+                }
+                arg_calls.push(quote! { .arg(format!(#fmt, #(#exprs),*)) });
+            }
+            Err(msg) => {
+                return syn::Error::new(Span::call_site(), msg)
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
+
+    let expanded = quote! {
+        make_rs::Cmd::new(#program) #(#arg_calls)*
+    };
+    TokenStream::from(expanded)
+}
+
+fn parse_word(word: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = word.char_indices().peekable();
+
+    while let Some((_, ch)) = chars.next() {
+        if ch == '{' {
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            let mut ident = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => ident.push(c),
+                    None => return Err(format!("unterminated `{{` in `{}`", word)),
+                }
+            }
+            let expr = syn::parse_str::<syn::Expr>(&ident)
+                .map_err(|_| format!("`{}` is not a valid expression in `{{{}}}`", ident, ident))?;
+            pieces.push(Piece::Var(expr));
+        } else {
+            literal.push(ch);
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
@@ -0,0 +1,175 @@
+//! Content-hash staleness detection, layered on top of mtime as a cheap
+//! pre-filter. A manifest maps each target to the hash of the inputs that
+//! produced it, so unchanged content is detected even when mtimes lie.
+
+use crate::{is_newer, Path, PathBuf, Result};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+const MANIFEST_DIR: &str = ".make_rs";
+const MANIFEST_FILE: &str = "cache.json";
+
+fn manifest_path() -> PathBuf {
+    Path::new(MANIFEST_DIR).join(MANIFEST_FILE)
+}
+
+/// Guards the manifest file's read-modify-write cycle: `run_graph` can run
+/// several commands touching the cache on separate threads at once, and
+/// without this each `save_manifest` would clobber the others' hashes.
+fn manifest_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn load_manifest() -> HashMap<String, u64> {
+    std::fs::read(manifest_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_manifest(manifest: &HashMap<String, u64>) -> Result<()> {
+    std::fs::create_dir_all(MANIFEST_DIR)?;
+    Ok(std::fs::write(
+        manifest_path(),
+        serde_json::to_vec_pretty(manifest)?,
+    )?)
+}
+
+/// FNV-1a over a byte slice: fast and non-cryptographic, which is all a
+/// staleness check needs.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// Hashes `inputs` together, folding in each path alongside its content so
+/// that adding, removing, or renaming an input also counts as a change.
+fn hash_inputs<I: AsRef<Path>>(inputs: &[I]) -> Result<u64> {
+    let mut combined = Vec::new();
+    for input in inputs {
+        combined.extend_from_slice(input.as_ref().to_string_lossy().as_bytes());
+        combined.extend_from_slice(&fnv1a(&std::fs::read(input.as_ref())?).to_le_bytes());
+    }
+    Ok(fnv1a(&combined))
+}
+
+fn cache_key<T: AsRef<Path>>(targets: &[T]) -> String {
+    targets
+        .iter()
+        .map(|t| t.as_ref().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Returns `true` if `targets` should be (re)built from `inputs`: any
+/// target is missing, or the content hash of `inputs` no longer matches
+/// the one recorded for `targets` in the manifest.
+///
+/// mtimes are checked first as a cheap pre-filter: if every target is
+/// already newer than every input, the hash check is skipped so large
+/// unchanged trees stay fast. Call [`record`] after a successful rebuild
+/// so the next call sees the new hash.
+pub fn needs_rebuild<T: AsRef<Path>, I: AsRef<Path>>(targets: &[T], inputs: &[I]) -> Result<bool> {
+    if targets.iter().any(|t| !t.as_ref().exists()) {
+        return Ok(true);
+    }
+
+    let mtime_clean = targets.iter().all(|t| {
+        inputs
+            .iter()
+            .all(|i| is_newer(t.as_ref(), i.as_ref()).unwrap_or(false))
+    });
+    if mtime_clean {
+        return Ok(false);
+    }
+
+    let hash = hash_inputs(inputs)?;
+    let _guard = manifest_lock().lock().unwrap();
+    Ok(load_manifest().get(&cache_key(targets)) != Some(&hash))
+}
+
+/// Records that the current content of `inputs` is what produced `targets`,
+/// so a later [`needs_rebuild`] call can skip work if nothing changed.
+pub fn record<T: AsRef<Path>, I: AsRef<Path>>(targets: &[T], inputs: &[I]) -> Result<()> {
+    let hash = hash_inputs(inputs)?;
+    let _guard = manifest_lock().lock().unwrap();
+    let mut manifest = load_manifest();
+    manifest.insert(cache_key(targets), hash);
+    save_manifest(&manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// `needs_rebuild`/`record` work against the process's current
+    /// directory, so tests that call them must not run concurrently with
+    /// each other.
+    fn in_fresh_dir<T>(f: impl FnOnce() -> T) -> T {
+        static LOCK: std::sync::OnceLock<Mutex<()>> = std::sync::OnceLock::new();
+        let _guard = LOCK.get_or_init(|| Mutex::new(())).lock().unwrap();
+
+        let dir = std::env::temp_dir().join(format!(
+            "make_rs-cache-test-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        let result = f();
+
+        std::env::set_current_dir(original).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        result
+    }
+
+    #[test]
+    fn needs_rebuild_when_target_missing() {
+        in_fresh_dir(|| {
+            std::fs::write("input.txt", b"hello").unwrap();
+            assert!(needs_rebuild(&["output.txt"], &["input.txt"]).unwrap());
+        });
+    }
+
+    #[test]
+    fn needs_rebuild_is_false_after_record_with_unchanged_input() {
+        in_fresh_dir(|| {
+            std::fs::write("input.txt", b"hello").unwrap();
+            std::fs::write("output.txt", b"built").unwrap();
+            record(&["output.txt"], &["input.txt"]).unwrap();
+
+            // Make the target look stale by mtime so the hash check, not
+            // the mtime pre-filter, is what's actually being exercised.
+            let past = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+            let file = std::fs::File::create("output.txt").unwrap();
+            file.set_modified(past).unwrap();
+
+            assert!(!needs_rebuild(&["output.txt"], &["input.txt"]).unwrap());
+        });
+    }
+
+    #[test]
+    fn needs_rebuild_is_true_after_input_content_changes() {
+        in_fresh_dir(|| {
+            std::fs::write("input.txt", b"hello").unwrap();
+            std::fs::write("output.txt", b"built").unwrap();
+            record(&["output.txt"], &["input.txt"]).unwrap();
+
+            let past = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+            std::fs::File::create("output.txt")
+                .unwrap()
+                .set_modified(past)
+                .unwrap();
+            std::fs::write("input.txt", b"changed").unwrap();
+
+            assert!(needs_rebuild(&["output.txt"], &["input.txt"]).unwrap());
+        });
+    }
+}
@@ -0,0 +1,140 @@
+//! Command construction and execution, built by the [`crate::cmd!`] macro.
+//!
+//! `Cmd` replaces the bare [`crate::run`] for anything beyond a one-off
+//! inherited-stdio call: it carries the full program and args so error
+//! messages can show exactly what ran, and offers `.read()` to capture
+//! output and `.ignore_status()` to opt out of the non-zero-exit check.
+
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::process::{Command, Stdio};
+
+pub struct Cmd {
+    program: String,
+    args: Vec<OsString>,
+    ignore_status: bool,
+}
+
+impl Cmd {
+    pub fn new(program: impl Into<String>) -> Cmd {
+        Cmd {
+            program: program.into(),
+            args: Vec::new(),
+            ignore_status: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Don't treat a non-zero exit status as an error in `.run()`/`.read()`.
+    pub fn ignore_status(mut self) -> Self {
+        self.ignore_status = true;
+        self
+    }
+
+    fn build(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    fn describe(&self) -> String {
+        let mut line = self.program.clone();
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&arg.to_string_lossy());
+        }
+        line
+    }
+
+    /// Runs the command with stdio inherited from this process, erroring if
+    /// it can't be started or (unless [`Cmd::ignore_status`]) exits non-zero.
+    pub fn run(self) -> Result<()> {
+        let status = self
+            .build()
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .with_context(|| format!("failed to run `{}`", self.describe()))?;
+
+        if !self.ignore_status && !status.success() {
+            return Err(anyhow!("`{}` exited with {}", self.describe(), status));
+        }
+        Ok(())
+    }
+
+    /// Runs the command and captures its trimmed stdout as a `String`.
+    /// Stderr is still inherited so failures are visible.
+    pub fn read(self) -> Result<String> {
+        let output = self
+            .build()
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("failed to run `{}`", self.describe()))?;
+
+        if !self.ignore_status && !output.status.success() {
+            return Err(anyhow!("`{}` exited with {}", self.describe(), output.status));
+        }
+        Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_captures_and_trims_stdout() {
+        let out = Cmd::new("printf").arg("  hello  \n").read().unwrap();
+        assert_eq!(out, "hello");
+    }
+
+    #[test]
+    fn args_extends_beyond_the_builder_arg() {
+        let out = Cmd::new("printf")
+            .arg("%s-%s")
+            .args(["a", "b"])
+            .read()
+            .unwrap();
+        assert_eq!(out, "a-b");
+    }
+
+    #[test]
+    fn run_errors_on_nonzero_exit() {
+        assert!(Cmd::new("false").run().is_err());
+    }
+
+    #[test]
+    fn read_errors_on_nonzero_exit() {
+        assert!(Cmd::new("false").read().is_err());
+    }
+
+    #[test]
+    fn ignore_status_suppresses_the_nonzero_exit_error() {
+        assert!(Cmd::new("false").ignore_status().run().is_ok());
+        assert_eq!(Cmd::new("false").ignore_status().read().unwrap(), "");
+    }
+
+    #[test]
+    fn run_errors_when_the_program_does_not_exist() {
+        assert!(Cmd::new("make_rs_test_nonexistent_binary").run().is_err());
+    }
+
+    #[test]
+    fn describe_includes_program_and_args_in_the_error() {
+        let err = Cmd::new("false").arg("--flag").run().unwrap_err();
+        assert!(err.to_string().contains("false --flag"));
+    }
+}
@@ -0,0 +1,335 @@
+//! File manipulation with explicit copy/remove/rename options and errors
+//! that carry the offending path.
+
+use crate::{cache, Path, Result};
+use anyhow::{anyhow, Context};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CopyOptions {
+    pub overwrite: bool,
+    pub ignore_if_exists: bool,
+    pub recursive: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            overwrite: true,
+            ignore_if_exists: false,
+            recursive: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOptions {
+    pub recursive: bool,
+    pub ignore_if_not_exists: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenameOptions {
+    pub overwrite: bool,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions { overwrite: true }
+    }
+}
+
+/// Copies `src` to `dest`, applying the same mtime-then-hash staleness
+/// check as [`crate::copy`] to each file so re-running a build doesn't redo
+/// unchanged work. With `options.recursive`, `src` may be a directory: its
+/// structure is recreated under `dest`, and `options` is applied to each
+/// file individually rather than once to the top-level directory, so a
+/// later run still picks up files that are new or changed.
+pub fn copy(src: impl AsRef<Path>, dest: impl AsRef<Path>, options: CopyOptions) -> Result<()> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+
+    if src.is_dir() {
+        if !options.recursive {
+            return Err(anyhow!(
+                "`{}` is a directory; pass CopyOptions {{ recursive: true, .. }} to copy it",
+                src.display()
+            ));
+        }
+        copy_dir(src, dest, options)
+    } else {
+        copy_file(src, dest, options)
+    }
+}
+
+fn copy_file(src: &Path, dest: &Path, options: CopyOptions) -> Result<()> {
+    if dest.exists() {
+        if options.ignore_if_exists {
+            return Ok(());
+        }
+        if !options.overwrite {
+            return Err(anyhow!("`{}` already exists", dest.display()));
+        }
+    }
+
+    if cache::needs_rebuild(&[dest], &[src])? {
+        std::fs::copy(src, dest).with_context(|| {
+            format!("failed to copy `{}` to `{}`", src.display(), dest.display())
+        })?;
+        cache::record(&[dest], &[src])?;
+    }
+    Ok(())
+}
+
+fn copy_dir(src: &Path, dest: &Path, options: CopyOptions) -> Result<()> {
+    std::fs::create_dir_all(dest)
+        .with_context(|| format!("failed to create directory `{}`", dest.display()))?;
+
+    let entries = std::fs::read_dir(src)
+        .with_context(|| format!("failed to read directory `{}`", src.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read entry in `{}`", src.display()))?;
+        let dest_path = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest_path, options)?;
+        } else {
+            copy_file(&entry.path(), &dest_path, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes a file, with the offending path attached to any error.
+pub fn remove_file(path: impl AsRef<Path>, options: RemoveOptions) -> Result<()> {
+    let path = path.as_ref();
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if not_found(&err, options.ignore_if_not_exists) => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("failed to remove file `{}`", path.display())),
+    }
+}
+
+/// Removes a directory. With `options.recursive`, removes its contents
+/// too; otherwise the directory must already be empty.
+pub fn remove_dir(path: impl AsRef<Path>, options: RemoveOptions) -> Result<()> {
+    let path = path.as_ref();
+    let result = if options.recursive {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_dir(path)
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if not_found(&err, options.ignore_if_not_exists) => Ok(()),
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to remove directory `{}`", path.display()))
+        }
+    }
+}
+
+/// Renames (moves) `src` to `dest`.
+pub fn rename(src: impl AsRef<Path>, dest: impl AsRef<Path>, options: RenameOptions) -> Result<()> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    if dest.exists() && !options.overwrite {
+        return Err(anyhow!("`{}` already exists", dest.display()));
+    }
+    std::fs::rename(src, dest).with_context(|| {
+        format!("failed to rename `{}` to `{}`", src.display(), dest.display())
+    })
+}
+
+/// Reads a path's metadata, with the offending path attached to any error.
+pub fn metadata(path: impl AsRef<Path>) -> Result<std::fs::Metadata> {
+    let path = path.as_ref();
+    std::fs::metadata(path)
+        .with_context(|| format!("failed to read metadata for `{}`", path.display()))
+}
+
+fn not_found(err: &std::io::Error, ignore_if_not_exists: bool) -> bool {
+    ignore_if_not_exists && err.kind() == std::io::ErrorKind::NotFound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// `copy`/`copy_file` go through [`cache::needs_rebuild`]/`record`,
+    /// which work against the process's current directory, so tests that
+    /// call them must not run concurrently with each other.
+    fn in_fresh_dir<T>(f: impl FnOnce() -> T) -> T {
+        static LOCK: std::sync::OnceLock<Mutex<()>> = std::sync::OnceLock::new();
+        let _guard = LOCK.get_or_init(|| Mutex::new(())).lock().unwrap();
+
+        let dir = std::env::temp_dir().join(format!(
+            "make_rs-fs-test-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        let result = f();
+
+        std::env::set_current_dir(original).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        result
+    }
+
+    #[test]
+    fn copy_copies_a_file_when_dest_is_missing() {
+        in_fresh_dir(|| {
+            std::fs::write("src.txt", b"hello").unwrap();
+            copy("src.txt", "dest.txt", CopyOptions::default()).unwrap();
+            assert_eq!(std::fs::read_to_string("dest.txt").unwrap(), "hello");
+        });
+    }
+
+    #[test]
+    fn copy_errors_on_a_directory_without_recursive() {
+        in_fresh_dir(|| {
+            std::fs::create_dir("src_dir").unwrap();
+            assert!(copy("src_dir", "dest_dir", CopyOptions::default()).is_err());
+        });
+    }
+
+    #[test]
+    fn copy_errors_when_dest_exists_and_overwrite_is_false() {
+        in_fresh_dir(|| {
+            std::fs::write("src.txt", b"hello").unwrap();
+            std::fs::write("dest.txt", b"old").unwrap();
+            let options = CopyOptions {
+                overwrite: false,
+                ..CopyOptions::default()
+            };
+            assert!(copy("src.txt", "dest.txt", options).is_err());
+            assert_eq!(std::fs::read_to_string("dest.txt").unwrap(), "old");
+        });
+    }
+
+    #[test]
+    fn copy_skips_when_dest_exists_and_ignore_if_exists_is_set() {
+        in_fresh_dir(|| {
+            std::fs::write("src.txt", b"hello").unwrap();
+            std::fs::write("dest.txt", b"old").unwrap();
+            let options = CopyOptions {
+                ignore_if_exists: true,
+                ..CopyOptions::default()
+            };
+            copy("src.txt", "dest.txt", options).unwrap();
+            assert_eq!(std::fs::read_to_string("dest.txt").unwrap(), "old");
+        });
+    }
+
+    #[test]
+    fn copy_dir_recreates_nested_structure() {
+        in_fresh_dir(|| {
+            std::fs::create_dir_all("src/nested").unwrap();
+            std::fs::write("src/top.txt", b"top").unwrap();
+            std::fs::write("src/nested/inner.txt", b"inner").unwrap();
+
+            let options = CopyOptions {
+                recursive: true,
+                ..CopyOptions::default()
+            };
+            copy("src", "dest", options).unwrap();
+
+            assert_eq!(std::fs::read_to_string("dest/top.txt").unwrap(), "top");
+            assert_eq!(
+                std::fs::read_to_string("dest/nested/inner.txt").unwrap(),
+                "inner"
+            );
+        });
+    }
+
+    #[test]
+    fn copy_dir_picks_up_a_file_added_after_the_first_run() {
+        in_fresh_dir(|| {
+            std::fs::create_dir("src").unwrap();
+            std::fs::write("src/a.txt", b"a").unwrap();
+
+            let options = CopyOptions {
+                recursive: true,
+                ignore_if_exists: true,
+                ..CopyOptions::default()
+            };
+            copy("src", "dest", options).unwrap();
+
+            std::fs::write("src/b.txt", b"b").unwrap();
+            copy("src", "dest", options).unwrap();
+
+            assert_eq!(std::fs::read_to_string("dest/a.txt").unwrap(), "a");
+            assert_eq!(std::fs::read_to_string("dest/b.txt").unwrap(), "b");
+        });
+    }
+
+    #[test]
+    fn remove_file_ignores_a_missing_file_when_asked() {
+        in_fresh_dir(|| {
+            let options = RemoveOptions {
+                ignore_if_not_exists: true,
+                ..RemoveOptions::default()
+            };
+            assert!(remove_file("missing.txt", options).is_ok());
+        });
+    }
+
+    #[test]
+    fn remove_file_errors_on_a_missing_file_by_default() {
+        in_fresh_dir(|| {
+            assert!(remove_file("missing.txt", RemoveOptions::default()).is_err());
+        });
+    }
+
+    #[test]
+    fn remove_dir_recursive_removes_contents() {
+        in_fresh_dir(|| {
+            std::fs::create_dir_all("dir/nested").unwrap();
+            std::fs::write("dir/nested/file.txt", b"x").unwrap();
+
+            let options = RemoveOptions {
+                recursive: true,
+                ..RemoveOptions::default()
+            };
+            remove_dir("dir", options).unwrap();
+            assert!(!Path::new("dir").exists());
+        });
+    }
+
+    #[test]
+    fn remove_dir_non_recursive_errors_when_not_empty() {
+        in_fresh_dir(|| {
+            std::fs::create_dir("dir").unwrap();
+            std::fs::write("dir/file.txt", b"x").unwrap();
+            assert!(remove_dir("dir", RemoveOptions::default()).is_err());
+        });
+    }
+
+    #[test]
+    fn rename_moves_a_file() {
+        in_fresh_dir(|| {
+            std::fs::write("src.txt", b"hello").unwrap();
+            rename("src.txt", "dest.txt", RenameOptions::default()).unwrap();
+            assert!(!Path::new("src.txt").exists());
+            assert_eq!(std::fs::read_to_string("dest.txt").unwrap(), "hello");
+        });
+    }
+
+    #[test]
+    fn rename_errors_when_dest_exists_and_overwrite_is_false() {
+        in_fresh_dir(|| {
+            std::fs::write("src.txt", b"hello").unwrap();
+            std::fs::write("dest.txt", b"old").unwrap();
+            let options = RenameOptions { overwrite: false };
+            assert!(rename("src.txt", "dest.txt", options).is_err());
+        });
+    }
+
+    #[test]
+    fn metadata_errors_on_a_missing_path() {
+        in_fresh_dir(|| {
+            assert!(metadata("missing.txt").is_err());
+        });
+    }
+}
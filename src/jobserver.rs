@@ -0,0 +1,110 @@
+//! GNU make jobserver client/server support.
+//!
+//! The jobserver protocol (see the GNU Make manual, "Job Slots") shares a
+//! pool of single-byte tokens through a pipe so that nested `make`/`cargo`
+//! invocations don't oversubscribe the machine. A process is always granted
+//! one implicit token for free; it must `acquire` a token from the pipe
+//! before starting any additional concurrent job, and `release` it (by
+//! dropping the [`Token`]) when that job finishes.
+
+use anyhow::Result;
+use std::io::{Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+
+/// A handle to a jobserver's token pipe, either inherited from a parent
+/// `make`/`cargo` via `MAKEFLAGS` or created fresh by this process.
+pub struct Jobserver {
+    read: std::fs::File,
+    write: std::fs::File,
+}
+
+/// A job slot acquired from a [`Jobserver`]. The token is returned to the
+/// pool when this is dropped.
+pub struct Token {
+    write: std::fs::File,
+    byte: u8,
+}
+
+impl Jobserver {
+    /// Looks for `--jobserver-auth=R,W` (or the older `--jobserver-fds=R,W`)
+    /// in `MAKEFLAGS` and attaches to the inherited pipe if present.
+    pub fn from_env() -> Option<Jobserver> {
+        let makeflags = std::env::var("MAKEFLAGS").ok()?;
+        let fds = makeflags.split_whitespace().find_map(|flag| {
+            flag.strip_prefix("--jobserver-auth=")
+                .or_else(|| flag.strip_prefix("--jobserver-fds="))
+        })?;
+        let (r, w) = fds.split_once(',')?;
+        let read_fd: RawFd = r.parse().ok()?;
+        let write_fd: RawFd = w.parse().ok()?;
+
+        // SAFETY: a parent make/cargo that advertises these fds in
+        // MAKEFLAGS guarantees they're an open pipe for our lifetime.
+        let read = unsafe { std::fs::File::from_raw_fd(read_fd) };
+        let write = unsafe { std::fs::File::from_raw_fd(write_fd) };
+
+        Some(Jobserver { read, write })
+    }
+
+    /// Creates a new jobserver sized for `jobs` concurrent tasks (one of
+    /// which is the implicit token every participant already holds), and
+    /// exports `--jobserver-auth=R,W` via `MAKEFLAGS` so that child
+    /// `make`/`make_rs` processes we spawn share the same pool.
+    pub fn create(jobs: usize) -> Result<Jobserver> {
+        let (read_fd, write_fd) = pipe()?;
+
+        // SAFETY: `pipe()` just returned these as freshly opened, unique fds.
+        let read = unsafe { std::fs::File::from_raw_fd(read_fd) };
+        let mut write = unsafe { std::fs::File::from_raw_fd(write_fd) };
+
+        for _ in 0..jobs.saturating_sub(1) {
+            write.write_all(b"+")?;
+        }
+
+        std::env::set_var(
+            "MAKEFLAGS",
+            format!(
+                "{} --jobserver-auth={},{}",
+                std::env::var("MAKEFLAGS").unwrap_or_default(),
+                read_fd,
+                write_fd
+            ),
+        );
+
+        Ok(Jobserver { read, write })
+    }
+
+    /// Attaches to an inherited jobserver, or creates one sized for `jobs`
+    /// if this process wasn't handed one.
+    pub fn from_env_or_create(jobs: usize) -> Result<Jobserver> {
+        match Jobserver::from_env() {
+            Some(server) => Ok(server),
+            None => Jobserver::create(jobs),
+        }
+    }
+
+    /// Blocks until a token is available and takes it from the pool. The
+    /// token is returned to the pool when the result is dropped.
+    pub fn acquire(&self) -> Result<Token> {
+        let mut byte = [0u8; 1];
+        (&self.read).read_exact(&mut byte)?;
+        Ok(Token {
+            write: self.write.try_clone()?,
+            byte: byte[0],
+        })
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        let _ = (&self.write).write_all(&[self.byte]);
+    }
+}
+
+fn pipe() -> Result<(RawFd, RawFd)> {
+    let mut fds = [0 as RawFd; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok((fds[0], fds[1]))
+}
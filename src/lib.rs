@@ -1,9 +1,23 @@
 pub use anyhow::Result;
 pub use std::path::{Path, PathBuf};
 
+mod cache;
+mod cmd;
+pub mod fs;
+mod jobserver;
+mod lock;
+mod watch;
+
+pub use cache::{needs_rebuild, record};
+pub use cmd::Cmd;
+pub use jobserver::{Jobserver, Token};
+pub use make_rs_macros::cmd;
+
 use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::process::ExitStatus;
+use std::sync::Arc;
 
 pub trait Target {
     type Item;
@@ -40,7 +54,7 @@ pub fn create_dir<P: AsRef<Path>>(path: P) -> Result<()> {
     Ok(std::fs::create_dir_all(path.as_ref())?)
 }
 
-fn is_newer<T: AsRef<Path>, B: AsRef<Path>>(target: T, base: B) -> Result<bool> {
+pub(crate) fn is_newer<T: AsRef<Path>, B: AsRef<Path>>(target: T, base: B) -> Result<bool> {
     let target_mtime = target.as_ref().metadata()?.modified()?;
     let base_mtime = base.as_ref().metadata()?.modified()?;
 
@@ -64,13 +78,17 @@ where
             dest.as_ref().to_path_buf()
         };
 
-        if is_newer(path.as_ref(), &dest).unwrap_or(true) {
-            let _ = std::fs::copy(path, &dest);
+        if cache::needs_rebuild(&[dest.as_path()], &[path.as_ref()])? {
+            std::fs::copy(path.as_ref(), &dest)?;
+            cache::record(&[dest.as_path()], &[path.as_ref()])?;
         }
     }
     Ok(())
 }
 
+/// Runs `cmd` with `args`, inheriting stdio. Prefer [`cmd!`] for new code:
+/// it captures the full program + args in error messages and supports
+/// `.read()`/`.ignore_status()`.
 pub fn run<I, S>(cmd: &str, args: I) -> Result<ExitStatus>
 where
     I: IntoIterator<Item = S>,
@@ -87,8 +105,16 @@ pub fn env_or<K: AsRef<OsStr>, D: AsRef<str>>(env: K, default: D) -> String {
     std::env::var(env).unwrap_or(default.as_ref().to_owned())
 }
 
+pub(crate) struct Command {
+    pub(crate) name: String,
+    pub(crate) deps: Vec<String>,
+    pub(crate) watches: Vec<String>,
+    pub(crate) pins: Vec<(String, lock::Resolver)>,
+    pub(crate) action: Arc<dyn Fn() -> Result<()> + Send + Sync>,
+}
+
 pub struct Maker {
-    commands: Vec<(String, Box<dyn FnOnce() -> Result<()>>)>,
+    commands: Vec<Command>,
     default: Option<String>,
 }
 
@@ -106,16 +132,66 @@ impl Maker {
     }
 
     pub fn cmd<S: AsRef<str>>(
+        self,
+        name: S,
+        cmd: impl Fn() -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.cmd_deps(name, [] as [String; 0], cmd)
+    }
+
+    /// Like [`Maker::cmd`], but declares that `deps` must run (at most once
+    /// each) before `name` does. `make()` resolves the requested target's
+    /// transitive dependencies, runs independent commands in parallel up to
+    /// the job limit, and errors out if the dependencies form a cycle.
+    pub fn cmd_deps<S: AsRef<str>, D: AsRef<str>>(
+        mut self,
+        name: S,
+        deps: impl IntoIterator<Item = D>,
+        cmd: impl Fn() -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.commands.push(Command {
+            name: name.as_ref().into(),
+            deps: deps.into_iter().map(|d| d.as_ref().into()).collect(),
+            watches: Vec::new(),
+            pins: Vec::new(),
+            action: Arc::new(cmd),
+        });
+        self
+    }
+
+    /// Registers a glob pattern or literal path that the most recently
+    /// added command reads from, for use by [`Maker::watch`]. The pattern
+    /// is re-matched against the filesystem on every change, so files added
+    /// later that match it are picked up without re-registering. Has no
+    /// effect on `make()`.
+    pub fn watches<S: AsRef<str>>(mut self, pattern: S) -> Self {
+        if let Some(last) = self.commands.last_mut() {
+            last.watches.push(pattern.as_ref().to_owned());
+        }
+        self
+    }
+
+    /// Declares that the most recently added command depends on an
+    /// external input named `name` (a downloaded archive, a tool version,
+    /// a remote URL) resolved by `resolve`. `make()` verifies `resolve()`
+    /// still hashes to what's recorded in `make_rs.lock` before running
+    /// the command; running the reserved `update` target re-resolves and
+    /// rewrites every declared pin.
+    pub fn pin<S: AsRef<str>>(
         mut self,
         name: S,
-        cmd: impl FnOnce() -> Result<()> + 'static,
+        resolve: impl Fn() -> Result<Vec<u8>> + Send + Sync + 'static,
     ) -> Self {
-        self.commands.push((name.as_ref().into(), Box::new(cmd)));
+        if let Some(last) = self.commands.last_mut() {
+            last.pins.push((name.as_ref().into(), Arc::new(resolve)));
+        }
         self
     }
 
-    pub fn make(mut self) {
-        let target = match std::env::args().skip(1).next() {
+    pub fn make(self) {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let (jobs, args) = take_jobs(args);
+        let target = match args.into_iter().find(|a| !a.starts_with('-')) {
             Some(cmd) => cmd,
             None => match &self.default {
                 Some(default) => default.clone(),
@@ -126,31 +202,227 @@ impl Maker {
             },
         };
 
-        match self
+        if !self.commands.iter().any(|c| c.name == target) {
+            if target == "update" {
+                let pins: Vec<(String, lock::Resolver)> = self
+                    .commands
+                    .iter()
+                    .flat_map(|c| c.pins.iter().cloned())
+                    .collect();
+                match lock::update(&pins) {
+                    Ok(()) => {
+                        eprintln!("Updated {} pin(s) in `{}`", pins.len(), lock::path().display())
+                    }
+                    Err(err) => {
+                        eprintln!("An error occurred:");
+                        eprintln!("{}", err);
+                    }
+                }
+            } else if target == "help" {
+                eprintln!("Available commands:");
+                for cmd in &self.commands {
+                    eprintln!("  {}", cmd.name);
+                }
+            } else {
+                eprintln!("Unknown command: {}", target);
+            }
+            return;
+        }
+
+        let by_name: HashMap<String, Command> = self
             .commands
+            .into_iter()
+            .map(|c| (c.name.clone(), c))
+            .collect();
+
+        let needed = match topo_order(&by_name, &target) {
+            Ok(needed) => needed,
+            Err(err) => {
+                eprintln!("An error occurred:");
+                eprintln!("{}", err);
+                return;
+            }
+        };
+        let pins: Vec<(String, lock::Resolver)> = needed
             .iter()
-            .enumerate()
-            .find(|(_, (cmd, _))| cmd == &target)
-        {
-            Some((i, _)) => {
-                if let Err(err) = self.commands.remove(i).1() {
-                    eprintln!("An error occurred:");
-                    eprintln!("{}", err);
-                }
+            .flat_map(|name| by_name[name].pins.iter().cloned())
+            .collect();
+        if let Err(err) = lock::verify(&pins) {
+            eprintln!("An error occurred:");
+            eprintln!("{}", err);
+            return;
+        }
+
+        if let Err(err) = run_graph(&by_name, &target, jobs) {
+            eprintln!("An error occurred:");
+            eprintln!("{}", err);
+        }
+    }
+
+    /// Watches every command's registered [`Maker::watches`] set and
+    /// re-runs a command (and its dependencies, up to the same `-jN` job
+    /// limit `make()` honors) as soon as any of its watched paths change,
+    /// debouncing bursts of filesystem events so one save doesn't trigger
+    /// more than one run. Runs until interrupted.
+    pub fn watch(self) -> Result<()> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let (jobs, _) = take_jobs(args);
+        let by_name: HashMap<String, Command> = self
+            .commands
+            .into_iter()
+            .map(|c| (c.name.clone(), c))
+            .collect();
+        watch::run(by_name, jobs)
+    }
+}
+
+/// Finds `-jN`/`-j N`, returning the job count (default 1) and the
+/// remaining args with the flag (and its separate value token, if any)
+/// removed, so a later scan for the target doesn't mistake the job count
+/// for the command name.
+fn take_jobs(mut args: Vec<String>) -> (usize, Vec<String>) {
+    let Some(i) = args.iter().position(|a| a.starts_with("-j")) else {
+        return (1, args);
+    };
+
+    let inline = args[i].strip_prefix("-j").unwrap().to_owned();
+    if !inline.is_empty() {
+        let jobs = inline.parse().unwrap_or(1);
+        args.remove(i);
+        return (jobs, args);
+    }
+
+    if i + 1 < args.len() {
+        let jobs = args[i + 1].parse().unwrap_or(1);
+        args.drain(i..=i + 1);
+        return (jobs, args);
+    }
+
+    args.remove(i);
+    (1, args)
+}
+
+/// Returns `target` and all of its transitive dependencies in an order
+/// where every dependency appears before the commands that need it.
+pub(crate) fn topo_order(commands: &HashMap<String, Command>, target: &str) -> Result<Vec<String>> {
+    let mut order = Vec::new();
+    let mut done = HashSet::new();
+    let mut visiting = HashSet::new();
+
+    fn visit(
+        name: &str,
+        commands: &HashMap<String, Command>,
+        done: &mut HashSet<String>,
+        visiting: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if !visiting.insert(name.to_owned()) {
+            return Err(anyhow!("dependency cycle detected at `{}`", name));
+        }
+        let cmd = commands
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown dependency `{}`", name))?;
+        for dep in &cmd.deps {
+            visit(dep, commands, done, visiting, order)?;
+        }
+        visiting.remove(name);
+        done.insert(name.to_owned());
+        order.push(name.to_owned());
+        Ok(())
+    }
+
+    visit(target, commands, &mut done, &mut visiting, &mut order)?;
+    Ok(order)
+}
+
+/// Runs `target` and its transitive dependencies, executing commands with
+/// satisfied dependencies in parallel up to `jobs` at a time. Cooperates
+/// with an inherited GNU make jobserver (or creates one) so that
+/// sub-`make`/sub-`make_rs` processes share the same token pool.
+pub(crate) fn run_graph(by_name: &HashMap<String, Command>, target: &str, jobs: usize) -> Result<()> {
+    // One job slot (the implicit token) always exists even if the caller
+    // passes 0.
+    let jobs = jobs.max(1);
+    let needed: HashSet<String> = topo_order(by_name, target)?.into_iter().collect();
+
+    let jobserver = Arc::new(Jobserver::from_env_or_create(jobs)?);
+    let mut completed: HashSet<String> = HashSet::new();
+    let mut in_flight: HashSet<String> = HashSet::new();
+    // `implicit_free` tracks whether the implicit token every participant
+    // gets for free is unoccupied; each task records in `used_implicit`
+    // whether it took it, so it can be handed to the next task once that
+    // one finishes instead of just the first task of the whole run.
+    let mut implicit_free = true;
+    let (tx, rx) = std::sync::mpsc::channel::<(String, bool, Result<()>)>();
+
+    while completed.len() < needed.len() {
+        // Never launch more tasks than `jobs` allows: the jobserver only
+        // has `jobs - 1` real tokens in its pipe (plus the one implicit
+        // slot), so launching an extra task here would leave it blocked in
+        // `acquire()` forever waiting for a token that was never created.
+        let slots = jobs.saturating_sub(in_flight.len());
+        let ready: Vec<String> = needed
+            .iter()
+            .filter(|name| !completed.contains(*name) && !in_flight.contains(*name))
+            .filter(|name| by_name[*name].deps.iter().all(|d| completed.contains(d)))
+            .take(slots)
+            .cloned()
+            .collect();
+
+        for name in ready {
+            let action = Arc::clone(&by_name[&name].action);
+            let used_implicit = implicit_free;
+            if used_implicit {
+                implicit_free = false;
             }
-            None => {
-                if &target == "help" {
-                    eprintln!("Available commands:");
-                    for (cmd, _) in self.commands {
-                        eprintln!("  {}", cmd);
-                    }
+            in_flight.insert(name.clone());
+            let tx = tx.clone();
+            let jobserver = Arc::clone(&jobserver);
+            std::thread::spawn(move || {
+                // Acquired in the worker, not the scheduler loop, so a task
+                // waiting on a token (e.g. cooperating with an externally
+                // shared jobserver) never blocks the scheduler from
+                // reaping other tasks that are already done.
+                let token = if used_implicit {
+                    None
                 } else {
-                    eprintln!("Unknown command: {}", target);
-                    return;
-                }
-            }
+                    match jobserver.acquire() {
+                        Ok(token) => Some(token),
+                        Err(err) => {
+                            let _ = tx.send((name, used_implicit, Err(err)));
+                            return;
+                        }
+                    }
+                };
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| action()))
+                    .unwrap_or_else(|_| Err(anyhow!("command `{}` panicked", name)));
+                drop(token);
+                let _ = tx.send((name, used_implicit, result));
+            });
+        }
+
+        if in_flight.is_empty() {
+            break;
         }
+
+        // Wake on whichever task actually finishes first, instead of
+        // waiting on launch order, so a fast sibling's dependents can be
+        // scheduled without waiting on an unrelated slow task.
+        let (name, used_implicit, result) = rx
+            .recv()
+            .map_err(|_| anyhow!("scheduler channel closed unexpectedly"))?;
+        in_flight.remove(&name);
+        if used_implicit {
+            implicit_free = true;
+        }
+        result?;
+        completed.insert(name);
     }
+
+    Ok(())
 }
 
 pub trait PathHelper {
@@ -172,3 +444,203 @@ impl<T, E> ResultHelper<E> for std::result::Result<T, E> {
         self.map(|_| ())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn command(name: &str, deps: &[&str]) -> Command {
+        Command {
+            name: name.to_owned(),
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+            watches: Vec::new(),
+            pins: Vec::new(),
+            action: Arc::new(|| Ok(())),
+        }
+    }
+
+    fn by_name(commands: Vec<Command>) -> HashMap<String, Command> {
+        commands.into_iter().map(|c| (c.name.clone(), c)).collect()
+    }
+
+    /// `run_graph` creates (or attaches to) a jobserver via the process-wide
+    /// `MAKEFLAGS` env var, so tests that call it must not run concurrently
+    /// with each other and must start from a clean slate.
+    fn run_graph_serialized(by_name: &HashMap<String, Command>, target: &str, jobs: usize) -> Result<()> {
+        static LOCK: std::sync::OnceLock<Mutex<()>> = std::sync::OnceLock::new();
+        let _guard = LOCK.get_or_init(|| Mutex::new(())).lock().unwrap();
+        std::env::remove_var("MAKEFLAGS");
+        run_graph(by_name, target, jobs)
+    }
+
+    #[test]
+    fn topo_order_runs_deps_before_dependents() {
+        let commands = by_name(vec![
+            command("build", &["compile", "assets"]),
+            command("compile", &[]),
+            command("assets", &[]),
+        ]);
+
+        let order = topo_order(&commands, "build").unwrap();
+        let pos = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert!(pos("compile") < pos("build"));
+        assert!(pos("assets") < pos("build"));
+        assert_eq!(order.last().unwrap(), "build");
+    }
+
+    #[test]
+    fn topo_order_detects_cycles() {
+        let commands = by_name(vec![command("a", &["b"]), command("b", &["a"])]);
+        assert!(topo_order(&commands, "a").is_err());
+    }
+
+    #[test]
+    fn topo_order_rejects_unknown_dependency() {
+        let commands = by_name(vec![command("build", &["missing"])]);
+        assert!(topo_order(&commands, "build").is_err());
+    }
+
+    #[test]
+    fn run_graph_runs_every_command_exactly_once() {
+        let calls: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let make_action = |name: &'static str, calls: Arc<Mutex<Vec<String>>>| {
+            Arc::new(move || {
+                calls.lock().unwrap().push(name.to_owned());
+                Ok(())
+            }) as Arc<dyn Fn() -> Result<()> + Send + Sync>
+        };
+
+        let commands = by_name(vec![
+            Command {
+                name: "build".to_owned(),
+                deps: vec!["compile".to_owned(), "assets".to_owned()],
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: make_action("build", Arc::clone(&calls)),
+            },
+            Command {
+                name: "compile".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: make_action("compile", Arc::clone(&calls)),
+            },
+            Command {
+                name: "assets".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: make_action("assets", Arc::clone(&calls)),
+            },
+        ]);
+
+        run_graph_serialized(&commands, "build", 2).unwrap();
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls.last().unwrap(), "build");
+    }
+
+    #[test]
+    fn run_graph_propagates_command_errors() {
+        let commands = by_name(vec![Command {
+            name: "fails".to_owned(),
+            deps: Vec::new(),
+            watches: Vec::new(),
+            pins: Vec::new(),
+            action: Arc::new(|| Err(anyhow!("boom"))),
+        }]);
+
+        assert!(run_graph_serialized(&commands, "fails", 1).is_err());
+    }
+
+    #[test]
+    fn run_graph_runs_independent_siblings_concurrently() {
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+        fn track() -> Result<()> {
+            let now = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_RUNNING.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        let commands = by_name(vec![
+            Command {
+                name: "root".to_owned(),
+                deps: vec!["a".to_owned(), "b".to_owned()],
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(|| Ok(())),
+            },
+            Command {
+                name: "a".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(track),
+            },
+            Command {
+                name: "b".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(track),
+            },
+        ]);
+
+        run_graph_serialized(&commands, "root", 2).unwrap();
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 2);
+    }
+
+    /// Regression test for a deadlock: with `jobs == 1` the jobserver's pipe
+    /// holds zero real tokens (only the one implicit slot), so launching
+    /// both of two simultaneously-ready siblings at once would leave the
+    /// second permanently blocked in `acquire()`. Both must still complete,
+    /// one at a time.
+    #[test]
+    fn run_graph_serializes_siblings_when_jobs_is_one() {
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+        fn track() -> Result<()> {
+            let now = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_RUNNING.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        let commands = by_name(vec![
+            Command {
+                name: "root".to_owned(),
+                deps: vec!["a".to_owned(), "b".to_owned()],
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(|| Ok(())),
+            },
+            Command {
+                name: "a".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(track),
+            },
+            Command {
+                name: "b".to_owned(),
+                deps: Vec::new(),
+                watches: Vec::new(),
+                pins: Vec::new(),
+                action: Arc::new(track),
+            },
+        ]);
+
+        run_graph_serialized(&commands, "root", 1).unwrap();
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,158 @@
+//! Reproducible-input pinning: a declared pin's resolved content is hashed
+//! and recorded in a checked-in `make_rs.lock`, verified on every run, and
+//! rewritten by the `update` sub-command.
+
+use crate::cache::fnv1a;
+use crate::Result;
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const LOCKFILE: &str = "make_rs.lock";
+
+/// Resolves an external input to the bytes that should be pinned, e.g. by
+/// downloading it, reading a tool's `--version` output, or hashing a URL's
+/// response.
+pub(crate) type Resolver = Arc<dyn Fn() -> Result<Vec<u8>> + Send + Sync>;
+
+pub(crate) fn path() -> &'static Path {
+    Path::new(LOCKFILE)
+}
+
+fn load() -> HashMap<String, u64> {
+    std::fs::read(LOCKFILE)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(lock: &HashMap<String, u64>) -> Result<()> {
+    Ok(std::fs::write(LOCKFILE, serde_json::to_vec_pretty(lock)?)?)
+}
+
+/// Verifies that every pin still resolves to the hash recorded in
+/// `make_rs.lock`, failing loudly on the first mismatch or missing pin.
+pub(crate) fn verify(pins: &[(String, Resolver)]) -> Result<()> {
+    if pins.is_empty() {
+        return Ok(());
+    }
+
+    let lock = load();
+    for (name, resolve) in pins {
+        let hash = fnv1a(&resolve()?);
+        match lock.get(name) {
+            Some(pinned) if *pinned == hash => {}
+            Some(_) => {
+                return Err(anyhow!(
+                    "pinned input `{}` no longer matches the hash recorded in `{}`; \
+                     run `update` if this change is expected",
+                    name,
+                    LOCKFILE
+                ))
+            }
+            None => {
+                return Err(anyhow!(
+                    "`{}` has no pin recorded for `{}`; run `update` to record one",
+                    LOCKFILE,
+                    name
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-resolves every pin and rewrites `make_rs.lock`.
+///
+/// Pin names must be unique across the whole `Maker`: they share one flat
+/// namespace in the lockfile, so two commands reusing the same name would
+/// silently overwrite each other's pin.
+pub(crate) fn update(pins: &[(String, Resolver)]) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for (name, _) in pins {
+        if !seen.insert(name) {
+            return Err(anyhow!(
+                "pin name `{}` is declared by more than one command; pin names must be unique",
+                name
+            ));
+        }
+    }
+
+    let mut lock = load();
+    for (name, resolve) in pins {
+        lock.insert(name.clone(), fnv1a(&resolve()?));
+    }
+    save(&lock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// `load`/`save` work against the process's current directory, so
+    /// tests that call `verify`/`update` must not run concurrently.
+    fn in_fresh_dir<T>(f: impl FnOnce() -> T) -> T {
+        static LOCK: std::sync::OnceLock<Mutex<()>> = std::sync::OnceLock::new();
+        let _guard = LOCK.get_or_init(|| Mutex::new(())).lock().unwrap();
+
+        let dir = std::env::temp_dir().join(format!(
+            "make_rs-lock-test-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        let result = f();
+
+        std::env::set_current_dir(original).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        result
+    }
+
+    fn pin(name: &str, content: &'static str) -> (String, Resolver) {
+        (name.to_owned(), Arc::new(move || Ok(content.as_bytes().to_vec())))
+    }
+
+    #[test]
+    fn verify_succeeds_with_no_pins_and_no_lockfile() {
+        in_fresh_dir(|| {
+            assert!(verify(&[]).is_ok());
+        });
+    }
+
+    #[test]
+    fn update_then_verify_succeeds() {
+        in_fresh_dir(|| {
+            let pins = [pin("tool", "v1.2.3")];
+            update(&pins).unwrap();
+            assert!(verify(&pins).is_ok());
+        });
+    }
+
+    #[test]
+    fn verify_fails_when_pin_was_never_recorded() {
+        in_fresh_dir(|| {
+            assert!(verify(&[pin("tool", "v1.2.3")]).is_err());
+        });
+    }
+
+    #[test]
+    fn verify_fails_when_resolved_content_changed_since_update() {
+        in_fresh_dir(|| {
+            update(&[pin("tool", "v1.2.3")]).unwrap();
+            assert!(verify(&[pin("tool", "v2.0.0")]).is_err());
+        });
+    }
+
+    #[test]
+    fn update_rejects_duplicate_pin_names() {
+        in_fresh_dir(|| {
+            let pins = [pin("tool", "v1"), pin("tool", "v2")];
+            assert!(update(&pins).is_err());
+        });
+    }
+}
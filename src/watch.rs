@@ -0,0 +1,119 @@
+//! Live incremental build loop: instead of running a target once, watch the
+//! paths each command declared via [`crate::Maker::watches`] and re-run a
+//! command (through the same dependency graph [`crate::Maker::make`] uses)
+//! whenever one of its watched paths changes.
+
+use crate::{run_graph, Command, Result};
+use anyhow::anyhow;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub(crate) fn run(by_name: HashMap<String, Command>, jobs: usize) -> Result<()> {
+    if by_name.values().all(|c| c.watches.is_empty()) {
+        return Err(anyhow!(
+            "no command registered a `.watches()` set; nothing to watch"
+        ));
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher: RecommendedWatcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    })?;
+
+    let mut watched_dirs = HashSet::new();
+    for command in by_name.values() {
+        for pattern in &command.watches {
+            let dir = root_dir(pattern);
+            if watched_dirs.insert(dir.clone()) {
+                watcher.watch(&dir, RecursiveMode::Recursive)?;
+            }
+        }
+    }
+
+    eprintln!("Watching for changes, press Ctrl-C to stop...");
+
+    let watched_names: Vec<String> = by_name
+        .iter()
+        .filter(|(_, c)| !c.watches.is_empty())
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    // Run every watched command once up front so the build starts caught up.
+    for name in &watched_names {
+        run_one(&by_name, name, jobs);
+    }
+
+    let mut changed: HashSet<PathBuf> = HashSet::new();
+    loop {
+        match rx.recv() {
+            Ok(event) => changed.extend(event.paths),
+            Err(_) => return Ok(()),
+        }
+
+        // Debounce: keep absorbing events for a short window so one save
+        // (which can fire several write/metadata events) triggers one run.
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => changed.extend(event.paths),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+
+        // `notify` reports absolute paths, while a relative pattern globs to
+        // relative ones, so resolve matches against the current directory.
+        let cwd = std::env::current_dir()?;
+        for name in &watched_names {
+            let matched = by_name[name]
+                .watches
+                .iter()
+                .any(|pattern| glob_matches(pattern).any(|p| changed.contains(&cwd.join(p))));
+            if matched {
+                run_one(&by_name, name, jobs);
+            }
+        }
+        changed.clear();
+    }
+}
+
+/// Runs `name` through [`run_graph`] (not its action directly), so a watched
+/// command still waits on its declared `deps`.
+fn run_one(by_name: &HashMap<String, Command>, name: &str, jobs: usize) {
+    if let Err(err) = run_graph(by_name, name, jobs) {
+        eprintln!("`{}` failed:", name);
+        eprintln!("{}", err);
+    }
+}
+
+/// Re-matches `pattern` against the filesystem, so a file added after
+/// watching started is picked up rather than missed.
+fn glob_matches(pattern: &str) -> impl Iterator<Item = PathBuf> {
+    glob::glob(pattern)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+}
+
+/// The longest literal (non-glob) prefix directory of `pattern`, to hand to
+/// `notify` as the directory to watch recursively.
+fn root_dir(pattern: &str) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if component.as_os_str().to_string_lossy().contains(['*', '?', '[']) {
+            break;
+        }
+        root.push(component);
+    }
+    if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    }
+}
@@ -0,0 +1,34 @@
+//! Integration tests for the `cmd!` macro, exercised through its actual
+//! expansion rather than unit-testing the proc macro's internals directly.
+
+#[test]
+fn bare_placeholder_interpolates_a_non_string_scalar() {
+    let port: u16 = 8080;
+    let out = make_rs::cmd!("printf {port}").read().unwrap();
+    assert_eq!(out, "8080");
+}
+
+#[test]
+fn embedded_placeholder_interpolates_next_to_literal_text() {
+    let port: u16 = 8080;
+    let out = make_rs::cmd!("printf {port}/tcp").read().unwrap();
+    assert_eq!(out, "8080/tcp");
+}
+
+#[test]
+fn spread_splices_every_item_as_its_own_arg() {
+    let rest = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    let out = make_rs::cmd!("printf %s-%s-%s {rest...}").read().unwrap();
+    assert_eq!(out, "a-b-c");
+}
+
+#[test]
+fn literal_only_template_runs_with_no_args() {
+    make_rs::cmd!("true").run().unwrap();
+}
+
+#[test]
+fn compile_fail_cases() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,3 @@
+fn main() {
+    let _ = make_rs::cmd!("");
+}